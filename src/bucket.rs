@@ -5,4 +5,6 @@ use crate::page::PageID;
 pub(crate) struct BucketMeta {
     pub(crate) root_page: PageID,
     pub(crate) next_int: u64,
+    // Tag of the comparator used to order this bucket's keys
+    pub(crate) comparator: u8,
 }
\ No newline at end of file
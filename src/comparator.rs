@@ -0,0 +1,115 @@
+use std::{cmp::Ordering, fmt};
+
+/// Function used to order the keys of a bucket.
+pub type CompareFn = fn(&[u8], &[u8]) -> Ordering;
+
+/// Tags below this value are reserved for the built-in comparators.
+const FIRST_CUSTOM_TAG: u8 = 128;
+
+/// Defines how the keys in a bucket are ordered.
+///
+/// Only the comparator's tag is stored in the bucket's metadata, so a database that uses
+/// custom comparators must register the same ones through
+/// [`OpenOptions::comparator`](crate::db::OpenOptions::comparator) every time it is opened.
+#[derive(Clone, Copy)]
+pub struct Comparator {
+    tag: u8,
+    cmp: CompareFn,
+}
+
+impl Comparator {
+    /// Orders keys byte by byte, which is the default for every bucket.
+    pub const LEXICOGRAPHIC: Comparator = Comparator {
+        tag: 0,
+        cmp: lexicographic,
+    };
+    /// Orders 8 byte keys as native-endian `u64`s, like LMDB's `INTEGERKEY`.
+    /// Keys of any other length sort after them, lexicographically.
+    ///
+    /// Integers encoded with [`keys`](crate::keys) are big-endian and already sort correctly
+    /// with [`Comparator::LEXICOGRAPHIC`], they must not be used with this comparator.
+    pub const U64: Comparator = Comparator {
+        tag: 1,
+        cmp: u64_ne,
+    };
+    /// Orders keys byte by byte in descending order.
+    pub const REVERSE: Comparator = Comparator {
+        tag: 2,
+        cmp: reverse,
+    };
+
+    /// Creates a user defined comparator.
+    ///
+    /// The tag must be at least 128, lower values are reserved for the built-in comparators.
+    pub const fn custom(tag: u8, cmp: CompareFn) -> Comparator {
+        Comparator { tag, cmp }
+    }
+
+    /// The tag stored in the metadata of buckets using this comparator.
+    pub fn tag(&self) -> u8 {
+        self.tag
+    }
+
+    pub fn compare(&self, a: &[u8], b: &[u8]) -> Ordering {
+        (self.cmp)(a, b)
+    }
+
+    pub(crate) fn is_builtin(&self) -> bool {
+        self.tag < FIRST_CUSTOM_TAG
+    }
+}
+
+impl Default for Comparator {
+    fn default() -> Self {
+        Comparator::LEXICOGRAPHIC
+    }
+}
+
+impl fmt::Debug for Comparator {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Comparator")
+            .field("tag", &self.tag)
+            .finish()
+    }
+}
+
+/// The comparators a database was opened with, looked up by tag.
+#[derive(Clone, Debug)]
+pub(crate) struct Comparators {
+    custom: Vec<Comparator>,
+}
+
+impl Comparators {
+    pub(crate) fn new(custom: Vec<Comparator>) -> Comparators {
+        Comparators { custom }
+    }
+
+    pub(crate) fn get(&self, tag: u8) -> Option<Comparator> {
+        [
+            Comparator::LEXICOGRAPHIC,
+            Comparator::U64,
+            Comparator::REVERSE,
+        ]
+        .iter()
+        .chain(self.custom.iter())
+        .find(|c| c.tag == tag)
+        .copied()
+    }
+}
+
+fn lexicographic(a: &[u8], b: &[u8]) -> Ordering {
+    a.cmp(b)
+}
+
+fn reverse(a: &[u8], b: &[u8]) -> Ordering {
+    b.cmp(a)
+}
+
+fn u64_ne(a: &[u8], b: &[u8]) -> Ordering {
+    match (<[u8; 8]>::try_from(a), <[u8; 8]>::try_from(b)) {
+        (Ok(a), Ok(b)) => u64::from_ne_bytes(a).cmp(&u64::from_ne_bytes(b)),
+        (Ok(_), Err(_)) => Ordering::Less,
+        (Err(_), Ok(_)) => Ordering::Greater,
+        (Err(_), Err(_)) => a.cmp(b),
+    }
+}
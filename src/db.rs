@@ -1,55 +1,91 @@
 #[cfg(unix)]
 use std::os::unix::fs::OpenOptionsExt;
 use std::{
-    fs::{File, OpenOptions as FileOpenOptions},
-    io::Write,
-    path::Path,
-    sync::{Arc, Mutex, RwLock},
+    collections::BTreeSet,
+    ffi::{OsStr, OsString},
+    fs::{self, File, OpenOptions as FileOpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex, RwLock,
+    },
 };
 
 use fs2::FileExt;
 use memmap2::Mmap;
 use page_size::get as get_page_size;
-use crate::errors::Result;
-use crate::freelist::Freelist;
-use crate::meta::Meta;
+use crate::comparator::{Comparator, Comparators};
+use crate::errors::{Error, LockResultExt, Result};
+use crate::freelist::{Freelist, FreelistStats};
+use crate::meta::{Meta, USER_META_SIZE};
 use crate::page::Page;
 
 const MAGIC_VALUE: u32 = 0x00AB_CDEF;
-const VERSION: u32 = 1;
+pub(crate) const VERSION: u32 = 4;
 
 pub(crate) const MIN_ALLOC_SIZE: u64 = 8 * 1024 * 1024;
 
 const DEFAULT_NUM_PAGES: usize = 32;
 
+const MIN_PAGESIZE: u64 = 1024;
+const MAX_PAGESIZE: u64 = 1024 * 1024;
+
+pub(crate) const MIN_META_PAGES: u32 = 2;
+const MAX_META_PAGES: u32 = 64;
 
 pub(crate) struct DBFlags {
     pub(crate) strict_mode: bool,
     pub(crate) mmap_populate: bool,
     pub(crate) direct_writes: bool,
+    pub(crate) huge_pages: bool,
+    pub(crate) read_mostly: bool,
+    // Largest size in bytes the file is allowed to grow to
+    pub(crate) max_size: Option<u64>,
+    // Size in bytes of the address space reserved for the mmap at open
+    pub(crate) map_size: Option<u64>,
+}
+
+impl DBFlags {
+    pub(crate) fn check_quota(&self, size: u64) -> Result<()> {
+        match self.max_size {
+            Some(max_size) if size > max_size => Err(Error::QuotaExceeded {
+                max_size,
+                requested: size,
+            }),
+            _ => Ok(()),
+        }
+    }
 }
 
 pub struct OpenOptions {
     pagesize: u64,
     num_pages: usize,
+    num_meta_pages: u32,
     flags: DBFlags,
+    comparators: Vec<Comparator>,
+    user_meta: Vec<u8>,
 }
 
 impl Default for OpenOptions {
     fn default() -> Self {
         // 获取当前系统内存页的大小
         let pagesize = get_page_size() as u64;
-        if pagesize < 1024 {
-            panic!("Pagesize must be 1024 bytes minimum");
-        }
         OpenOptions {
             pagesize,
             num_pages: DEFAULT_NUM_PAGES,
+            num_meta_pages: MIN_META_PAGES,
             flags: DBFlags {
                 strict_mode: false,
                 mmap_populate: false,
                 direct_writes: false,
+                huge_pages: false,
+                read_mostly: false,
+                max_size: None,
+                map_size: None,
             },
+            comparators: Vec::new(),
+            user_meta: Vec::new(),
         }
     }
 }
@@ -60,23 +96,24 @@ impl OpenOptions {
         Self::default()
     }
 
-    //  设置pagesize
+    //  设置pagesize, 必须是1024到1MiB之间的2的幂, 否则open时返回Error::InvalidPagesize
     pub fn pagesize(mut self, pagesize: u64) -> Self {
-        if pagesize < 1024 {
-            panic!("Pagesize must be 1024 bytes minimum");
-        }
         self.pagesize = pagesize;
         self
     }
 
+    // 小于num_meta_pages + 2会在open时返回Error::InvalidNumPages
     pub fn num_pages(mut self, num_pages: usize) -> Self {
-        if num_pages < 4 {
-            panic!("Must have a minimum of 4 pages");
-        }
         self.num_pages = num_pages;
         self
     }
 
+    // 创建数据库时meta page的数量, 提交时轮流写入, 不在2到64之间会在open时返回Error::InvalidNumMetaPages
+    pub fn num_meta_pages(mut self, num_meta_pages: u32) -> Self {
+        self.num_meta_pages = num_meta_pages;
+        self
+    }
+
     pub fn strict_mode(mut self, strict_mode: bool) -> Self {
         self.flags.strict_mode = strict_mode;
         self
@@ -92,20 +129,88 @@ impl OpenOptions {
         self
     }
 
+    // 在Linux上为mmap申请透明大页, 减少大数据库的TLB压力, 其他平台上忽略
+    pub fn huge_pages(mut self, huge_pages: bool) -> Self {
+        self.flags.huge_pages = huge_pages;
+        self
+    }
+
+    // 针对以读为主的数据库: 打开mmap_populate, 并提示OS预读meta, freelist和root bucket的page
+    pub fn read_mostly(mut self, read_mostly: bool) -> Self {
+        self.flags.read_mostly = read_mostly;
+        self
+    }
+
+    // 限制数据库文件的最大字节数, 超过时写事务返回Error::QuotaExceeded
+    pub fn max_size(mut self, max_size: u64) -> Self {
+        self.flags.max_size = Some(max_size);
+        self
+    }
+
+    // 预留map_size字节的虚拟地址空间, 文件在这个大小以内增长时不需要重新mmap (仅Unix)
+    pub fn map_size(mut self, map_size: u64) -> Self {
+        self.flags.map_size = Some(map_size);
+        self
+    }
+
+    // 注册自定义的比较函数, bucket中保存的comparator tag在open时必须能找到对应的比较函数
+    pub fn comparator(mut self, comparator: Comparator) -> Self {
+        self.comparators.push(comparator);
+        self
+    }
+
+    // 创建数据库时写入meta page的应用元数据, 最多64字节, 打开已有的数据库时忽略
+    pub fn user_meta(mut self, user_meta: &[u8]) -> Self {
+        self.user_meta = user_meta.to_vec();
+        self
+    }
+
 
-    pub fn open<P: AsRef<Path>>(self, path: P) -> Result<DB> {
+    pub fn open<P: AsRef<Path>>(mut self, path: P) -> Result<DB> {
+        check_pagesize(self.pagesize)?;
+        if !(MIN_META_PAGES..=MAX_META_PAGES).contains(&self.num_meta_pages) {
+            return Err(Error::InvalidNumMetaPages(self.num_meta_pages));
+        }
+        // The meta pages are followed by the freelist page and the root bucket's page
+        let min_pages = self.num_meta_pages as usize + 2;
+        if self.num_pages < min_pages {
+            return Err(Error::InvalidNumPages {
+                num_pages: self.num_pages,
+                min: min_pages,
+            });
+        }
+        if let Some(c) = self.comparators.iter().find(|c| c.is_builtin()) {
+            return Err(Error::ReservedComparator(c.tag()));
+        }
+        let mut tags = BTreeSet::new();
+        if let Some(c) = self.comparators.iter().find(|c| !tags.insert(c.tag())) {
+            return Err(Error::DuplicateComparator(c.tag()));
+        }
+        if self.user_meta.len() > USER_META_SIZE {
+            return Err(Error::UserMetaTooLarge(self.user_meta.len()));
+        }
         let path: &Path = path.as_ref();
+        // Register before creating or opening the file, a second open would otherwise
+        // block forever on the file lock, or race with this one to create the file.
+        let open_path = OpenPath::register(path)?;
         let file = if !path.exists() {
+            self.flags
+                .check_quota(pages_size(self.pagesize, self.num_pages)?)?;
             init_file(
                 path,
                 self.pagesize,
                 self.num_pages,
+                self.num_meta_pages,
+                &self.user_meta,
                 self.flags.direct_writes,
             )?
         } else {
             open_file(path, false, self.flags.direct_writes)?
         };
-        let db = DBInner::open(file, self.pagesize, self.flags)?;
+        // read_mostly implies mmap_populate, whatever order the options were set in
+        self.flags.mmap_populate |= self.flags.read_mostly;
+        let comparators = Comparators::new(self.comparators);
+        let db = DBInner::open(file, self.pagesize, self.flags, comparators, open_path)?;
         Ok(DB {
             inner: Arc::new(db),
         })
@@ -113,11 +218,40 @@ impl OpenOptions {
 }
 
 
+/// A handle to an open database.
+///
+/// `DB` is `Send` and `Sync`, and cloning it is cheap: every clone shares the same underlying
+/// file, so the usual way to use a database from several threads is to give each one a clone.
 #[derive(Clone)]
 pub struct DB {
     pub(crate) inner: Arc<DBInner>,
 }
 
+/// Header fields and state of a database, returned by [`DB::info`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DBInfo {
+    /// File format version the database was created with
+    pub version: u32,
+    /// Whether the meta page starts with the expected magic value
+    pub magic_valid: bool,
+    /// Pagesize the database was created with
+    pub pagesize: u64,
+    /// Number of redundant meta pages
+    pub num_meta_pages: u32,
+    /// ID of the last committed transaction
+    pub tx_id: u64,
+    /// Number of pages in use, including free ones
+    pub num_pages: u64,
+    /// Number of pages on the freelist
+    pub freelist_len: usize,
+}
+
+// Fails to compile if a change to DBInner makes DB unusable across threads.
+const _: fn() = || {
+    fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<DB>();
+};
+
 impl DB {
     pub fn open<P: AsRef<Path>>(path: P) -> Result<DB> {
         OpenOptions::new().open(path)
@@ -131,6 +265,50 @@ impl DB {
         self.inner.pagesize
     }
 
+    /// Returns a summary of the database's header and state, for health checks and debugging.
+    pub fn info(&self) -> Result<DBInfo> {
+        let meta = self.inner.meta()?;
+        Ok(DBInfo {
+            version: meta.version,
+            magic_valid: meta.magic == MAGIC_VALUE,
+            pagesize: meta.pagesize,
+            num_meta_pages: meta.num_meta_pages,
+            tx_id: meta.tx_id,
+            num_pages: meta.num_pages,
+            freelist_len: self.inner.freelist.lock().recover().len(),
+        })
+    }
+
+    /// Returns statistics about the free and pending pages.
+    pub fn freelist_stats(&self) -> FreelistStats {
+        self.inner.freelist.lock().recover().stats()
+    }
+
+    /// Frees the pending pages that no open read transaction can see anymore,
+    /// returning how many pages were released.
+    ///
+    /// Call this after closing a long running read transaction to make its pages
+    /// available for reuse right away instead of after the next commit.
+    pub fn release_pending(&self) -> Result<usize> {
+        // Pages freed by a write transaction that has not committed yet must stay pending,
+        // so never release past the last committed transaction.
+        let last_committed = self.inner.meta()?.tx_id;
+        let open_ro_txs = self.inner.open_ro_txs.lock().recover();
+        let oldest_reader = open_ro_txs.iter().min().copied().unwrap_or(u64::MAX);
+        let released = self
+            .inner
+            .freelist
+            .lock()
+            .recover()
+            .release(oldest_reader.min(last_committed + 1));
+        Ok(released)
+    }
+
+    /// Returns the application metadata stored in the last committed meta page.
+    pub fn user_meta(&self) -> Result<Vec<u8>> {
+        Ok(self.inner.meta()?.user_meta()?.to_vec())
+    }
+
     #[doc(hidden)]
     pub fn check(&self) -> Result<()> {
         self.tx(false)?.check()
@@ -145,15 +323,26 @@ pub(crate) struct DBInner {
     pub(crate) file: Mutex<File>,
     pub(crate) open_ro_txs: Mutex<Vec<u64>>,
     pub(crate) flags: DBFlags,
+    pub(crate) comparators: Comparators,
+    pub(crate) path: OpenPath,
+    // Size of the file in bytes, the mapping is larger when map_size reserves address space
+    pub(crate) file_size: AtomicU64,
 
     pub(crate) pagesize: u64,
 }
 
 impl DBInner {
-    pub(crate) fn open(file: File, pagesize: u64, flags: DBFlags) -> Result<DBInner> {
+    pub(crate) fn open(
+        file: File,
+        pagesize: u64,
+        flags: DBFlags,
+        comparators: Comparators,
+        path: OpenPath,
+    ) -> Result<DBInner> {
         // 获取一个独占锁
         file.lock_exclusive()?;
-        let mmap = mmap(&file, flags.mmap_populate)?;
+        let file_size = AtomicU64::new(file.metadata()?.len());
+        let mmap = mmap(&file, &flags)?;
         let mmap = Mutex::new(Arc::new(mmap));
         let db = DBInner {
             data: mmap,
@@ -164,14 +353,25 @@ impl DBInner {
 
             pagesize,
             flags,
+            comparators,
+            path,
+            file_size,
         };
         {
             let meta = db.meta()?;
-            let data = db.data.lock()?;
-            let free_pages = Page::from_buf(&data, meta.freelist_page, pagesize).freelist();
+            db.comparator(meta.root.comparator)?;
+            let data = db.data.lock().recover();
+            let free_pages =
+                Page::from_buf_checked(db.file_data(&data), meta.freelist_page, pagesize)
+                    .map_err(|e| e.corrupted(meta.tx_id, &[]))?
+                    .freelist();
 
             if !free_pages.is_empty() {
-                db.freelist.lock()?.init(free_pages);
+                db.freelist.lock().recover().init(free_pages);
+            }
+            if db.flags.read_mostly {
+                let file_size = db.file_size.load(Ordering::Acquire);
+                advise_hot_pages(&data, file_size, &meta, pagesize)?;
             }
         }
 
@@ -179,82 +379,273 @@ impl DBInner {
     }
 
     pub(crate) fn resize(&self, file: &File, new_size: u64) -> Result<Arc<Mmap>> {
+        self.flags.check_quota(new_size)?;
         // 预分配空间
         file.allocate(new_size)?;
-        let _lock_write_guard = self.mmap_lock.write()?;
-        let mut data = self.data.lock()?;
-        let mmap = mmap(file, self.flags.mmap_populate)?;
+        // Make the new size durable before any page is written past the old end of the file.
+        // fdatasync is enough, it persists the size along with the data.
+        file.sync_data()?;
+        sync_dir(&self.path.0)?;
+        self.file_size.fetch_max(new_size, Ordering::AcqRel);
+        // The reserved mapping already covers the new size, so readers can keep using it.
+        // Only Unix reserves address space, see mmap().
+        #[cfg(unix)]
+        if new_size <= self.flags.map_size.unwrap_or(0) {
+            return Ok(self.data.lock().recover().clone());
+        }
+        // Read before taking the locks below, meta() locks the data itself
+        let meta = if self.flags.read_mostly {
+            Some(self.meta()?)
+        } else {
+            None
+        };
+        let _lock_write_guard = self.mmap_lock.write().recover();
+        let mut data = self.data.lock().recover();
+        let mmap = mmap(file, &self.flags)?;
+        if let Some(meta) = &meta {
+            let file_size = self.file_size.load(Ordering::Acquire);
+            advise_hot_pages(&mmap, file_size, meta, self.pagesize)?;
+        }
         *data = Arc::new(mmap);
         Ok(data.clone())
     }
 
     pub(crate) fn meta(&self) -> Result<Meta> {
-        let data = self.data.lock()?;
-        let meta1 = Page::from_buf(&data, 0, self.pagesize).meta();
-
-        // Double check that we have the right pagesize before we read the second page.
-        if meta1.valid() && meta1.pagesize != self.pagesize {
-            assert_eq!(
-                meta1.pagesize, self.pagesize,
-                "Invalid pagesize from meta1 {}. Expected {}.",
-                meta1.pagesize, self.pagesize
-            );
+        let data = self.data.lock().recover();
+        let data = self.file_data(&data);
+        let file_pages = data.len() as u64 / self.pagesize;
+        Ok(Meta::newest(data, self.pagesize, file_pages)?.clone())
+    }
+
+    // The part of the mapping that is backed by the file. Reading past it raises SIGBUS,
+    // so pages must only be looked up in this slice, not in the whole mapping.
+    pub(crate) fn file_data<'a>(&self, data: &'a Mmap) -> &'a [u8] {
+        let file_size = self.file_size.load(Ordering::Acquire);
+        &data[..file_size.min(data.len() as u64) as usize]
+    }
+
+    pub(crate) fn comparator(&self, tag: u8) -> Result<Comparator> {
+        self.comparators
+            .get(tag)
+            .ok_or(Error::UnknownComparator(tag))
+    }
+}
+
+
+// A power of two keeps every page aligned for its header
+pub(crate) fn check_pagesize(pagesize: u64) -> Result<()> {
+    if !(MIN_PAGESIZE..=MAX_PAGESIZE).contains(&pagesize) || !pagesize.is_power_of_two() {
+        return Err(Error::InvalidPagesize(pagesize));
+    }
+    Ok(())
+}
+
+// Size in bytes of `num_pages` pages
+fn pages_size(pagesize: u64, num_pages: usize) -> Result<u64> {
+    pagesize
+        .checked_mul(num_pages as u64)
+        .ok_or(Error::FileTooLarge {
+            pagesize,
+            num_pages,
+        })
+}
+
+// Canonical paths of all databases currently open in this process
+static OPEN_PATHS: Mutex<BTreeSet<PathBuf>> = Mutex::new(BTreeSet::new());
+
+/// Registration of an open database's path, removed again when the database is dropped.
+pub(crate) struct OpenPath(PathBuf);
+
+impl OpenPath {
+    fn register(path: &Path) -> Result<OpenPath> {
+        let path = canonical_path(path)?;
+        if !OPEN_PATHS.lock().recover().insert(path.clone()) {
+            return Err(Error::AlreadyOpen(path));
         }
+        Ok(OpenPath(path))
+    }
+}
 
-        let meta2 = Page::from_buf(&data, 1, self.pagesize).meta();
-        let meta = match (meta1.valid(), meta2.valid()) {
-            (true, true) => {
-                assert_eq!(
-                    meta1.pagesize, self.pagesize,
-                    "Invalid pagesize from meta1 {}. Expected {}.",
-                    meta1.pagesize, self.pagesize
-                );
-                assert_eq!(
-                    meta2.pagesize, self.pagesize,
-                    "Invalid pagesize from meta2 {}. Expected {}.",
-                    meta2.pagesize, self.pagesize
-                );
-                if meta1.tx_id > meta2.tx_id {
-                    meta1
-                } else {
-                    meta2
-                }
-            }
-            (true, false) => {
-                assert_eq!(
-                    meta1.pagesize, self.pagesize,
-                    "Invalid pagesize from meta1 {}. Expected {}.",
-                    meta1.pagesize, self.pagesize
-                );
-                meta1
-            }
-            (false, true) => {
-                assert_eq!(
-                    meta2.pagesize, self.pagesize,
-                    "Invalid pagesize from meta2 {}. Expected {}.",
-                    meta2.pagesize, self.pagesize
-                );
-                meta2
+impl Drop for OpenPath {
+    fn drop(&mut self) {
+        OPEN_PATHS.lock().recover().remove(&self.0);
+    }
+}
+
+// Canonicalizes `path`, which may not exist yet, through its parent directory.
+fn canonical_path(path: &Path) -> Result<PathBuf> {
+    if path.exists() {
+        return Ok(path.canonicalize()?);
+    }
+    Ok(parent_dir(path)
+        .canonicalize()?
+        .join(path.file_name().unwrap_or_default()))
+}
+
+// Directory containing `path`, a bare file name lives in the current directory.
+fn parent_dir(path: &Path) -> &Path {
+    match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    }
+}
+
+fn init_file(
+    path: &Path,
+    pagesize: u64,
+    num_pages: usize,
+    num_meta_pages: u32,
+    user_meta: &[u8],
+    direct_write: bool,
+) -> Result<File> {
+    remove_stale_temp_files(path)?;
+    // Build the database under a temporary name and only link it into place once it is
+    // complete, so a crash during creation never leaves a partial database at `path`.
+    let tmp_path = temp_path(path);
+    let file = write_new_file(
+        &tmp_path,
+        pagesize,
+        num_pages,
+        num_meta_pages,
+        user_meta,
+        direct_write,
+    )
+    .and_then(|file| {
+        // Unlike a rename, linking fails if someone else created the database meanwhile.
+        match fs::hard_link(&tmp_path, path) {
+            Ok(()) => Ok(file),
+            Err(e) if hard_links_unsupported(&e) => {
+                drop(file);
+                write_new_file(
+                    path,
+                    pagesize,
+                    num_pages,
+                    num_meta_pages,
+                    user_meta,
+                    direct_write,
+                )
             }
-            (false, false) => panic!("NO VALID META PAGES"),
-        };
+            Err(e) => Err(e.into()),
+        }
+    });
+    let removed = remove_if_exists(&tmp_path);
+    let file = file?;
+    removed?;
+    sync_dir(path)?;
+    // DBInner::open locks the file again, and locks do not nest on Windows
+    FileExt::unlock(&file)?;
+    Ok(file)
+}
+
+// FAT and exFAT refuse links with EPERM, some FUSE and network mounts do not implement them.
+#[cfg(unix)]
+fn hard_links_unsupported(e: &io::Error) -> bool {
+    matches!(
+        e.raw_os_error(),
+        Some(libc::EPERM | libc::EOPNOTSUPP | libc::ENOSYS)
+    )
+}
+
+// ERROR_INVALID_FUNCTION from FAT and exFAT, ERROR_NOT_SUPPORTED from network shares.
+#[cfg(windows)]
+fn hard_links_unsupported(e: &io::Error) -> bool {
+    matches!(e.raw_os_error(), Some(1 | 50))
+}
+
+static TEMP_FILES: AtomicU64 = AtomicU64::new(0);
 
-        Ok(meta.clone())
+// Hidden file next to `path`, unique to this call.
+fn temp_path(path: &Path) -> PathBuf {
+    let mut name = temp_prefix(path);
+    let n = TEMP_FILES.fetch_add(1, Ordering::Relaxed);
+    name.push(format!("{}.{}.tmp", std::process::id(), n));
+    path.with_file_name(name)
+}
+
+fn temp_prefix(path: &Path) -> OsString {
+    let mut prefix = OsString::from(".");
+    prefix.push(path.file_name().unwrap_or_default());
+    prefix.push(".");
+    prefix
+}
+
+// Whether `name` is `{prefix}{pid}.{counter}.tmp`, as created by temp_path.
+fn is_temp_file(name: &OsStr, prefix: &OsStr) -> bool {
+    let is_number = |s: &str| !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit());
+    match (name.to_str(), prefix.to_str()) {
+        (Some(name), Some(prefix)) => name
+            .strip_prefix(prefix)
+            .and_then(|rest| rest.strip_suffix(".tmp"))
+            .and_then(|ids| ids.split_once('.'))
+            .is_some_and(|(pid, n)| is_number(pid) && is_number(n)),
+        _ => false,
+    }
+}
+
+// Removes the temp files of creations of `path` that crashed. A creator keeps its temp file
+// locked until it has been linked into place, so a temp file that can be locked is stale.
+fn remove_stale_temp_files(path: &Path) -> Result<()> {
+    let prefix = temp_prefix(path);
+    for entry in fs::read_dir(parent_dir(path))? {
+        let entry = entry?;
+        if !is_temp_file(&entry.file_name(), &prefix) {
+            continue;
+        }
+        let stale = FileOpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(entry.path())
+            .and_then(|file| file.try_lock_exclusive())
+            .is_ok();
+        if stale {
+            remove_if_exists(&entry.path())?;
+        }
+    }
+    Ok(())
+}
+
+fn remove_if_exists(path: &Path) -> Result<()> {
+    match fs::remove_file(path) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e.into()),
+        _ => Ok(()),
     }
 }
 
+// Persists the directory entry of `path`, without it a new file can vanish after a power loss.
+#[cfg(unix)]
+fn sync_dir(path: &Path) -> Result<()> {
+    File::open(parent_dir(path))?.sync_all()?;
+    Ok(())
+}
 
-fn init_file(path: &Path, pagesize: u64, num_pages: usize, direct_write: bool) -> Result<File> {
+// Directories cannot be opened for syncing on Windows, NTFS journals their entries itself.
+#[cfg(windows)]
+fn sync_dir(_path: &Path) -> Result<()> {
+    Ok(())
+}
+
+fn write_new_file(
+    path: &Path,
+    pagesize: u64,
+    num_pages: usize,
+    num_meta_pages: u32,
+    user_meta: &[u8],
+    direct_write: bool,
+) -> Result<File> {
     let mut file = open_file(path, true, direct_write)?;
-    file.allocate(pagesize * (num_pages as u64))?;
-    let mut buf = vec![0; (pagesize * 4) as usize];
+    // Tells remove_stale_temp_files that the file is still being written
+    file.lock_exclusive()?;
+    file.allocate(pages_size(pagesize, num_pages)?)?;
+    let freelist_page = num_meta_pages as u64;
+    let root_page = freelist_page + 1;
+    let mut buf = vec![0; pages_size(pagesize, root_page as usize + 1)? as usize];
     let mut get_page = |index: u64| {
         #[allow(clippy::cast_ptr_alignment)]
         unsafe {
             &mut *(&mut buf[(index * pagesize) as usize] as *mut u8 as *mut Page)
         }
     };
-    for i in 0..2 {
+    for i in 0..freelist_page {
         let page = get_page(i);
         page.id = i;
         page.page_type = Page::TYPE_META;
@@ -262,29 +653,33 @@ fn init_file(path: &Path, pagesize: u64, num_pages: usize, direct_write: bool) -
         m.meta_page = i as u32;
         m.magic = MAGIC_VALUE;
         m.version = VERSION;
+        m.num_meta_pages = num_meta_pages;
         m.pagesize = pagesize;
-        m.freelist_page = 2;
+        m.freelist_page = freelist_page;
         m.root = BucketMeta {
-            root_page: 3,
+            root_page,
             next_int: 0,
+            comparator: Comparator::LEXICOGRAPHIC.tag(),
         };
-        m.num_pages = 4;
+        m.num_pages = root_page + 1;
+        m.set_user_meta(user_meta)?;
         m.hash = m.hash_self();
     }
 
-    let p = get_page(2);
-    p.id = 2;
+    let p = get_page(freelist_page);
+    p.id = freelist_page;
     p.page_type = Page::TYPE_FREELIST;
     p.count = 0;
 
-    let p = get_page(3);
-    p.id = 3;
+    let p = get_page(root_page);
+    p.id = root_page;
     p.page_type = Page::TYPE_LEAF;
     p.count = 0;
 
     file.write_all(&buf[..])?;
     file.flush()?;
-    file.sync_all()?;
+    // The size is persisted along with the data, and sync_dir persists the name
+    file.sync_data()?;
     Ok(file)
 }
 
@@ -318,22 +713,68 @@ fn open_file<P: AsRef<Path>>(path: P, create: bool, direct_write: bool) -> Resul
 
 // Have different mmap functions for Unix and Windows
 #[cfg(unix)]
-fn mmap(file: &File, populate: bool) -> Result<Mmap> {
+fn mmap(file: &File, flags: &DBFlags) -> Result<Mmap> {
     use memmap2::MmapOptions;
 
     let mut options = MmapOptions::new();
-    if populate {
+    if flags.mmap_populate {
         options.populate();
     }
+    if let Some(map_size) = flags.map_size {
+        // Map past the end of the file so it can grow without a new mmap.
+        // DBInner::file_data keeps page lookups within the part backed by the file.
+        let file_size = file.metadata()?.len();
+        options.len(map_size.max(file_size) as usize);
+    }
     let mmap = unsafe { options.map(file)? };
     // On Unix we advice the OS that page access will be random.
     mmap.advise(memmap2::Advice::Random)?;
+    #[cfg(target_os = "linux")]
+    if flags.huge_pages {
+        // Only a hint, kernels built without transparent huge pages reject it with EINVAL
+        match mmap.advise(memmap2::Advice::HugePage) {
+            Err(e) if e.raw_os_error() != Some(libc::EINVAL) => return Err(e.into()),
+            _ => {}
+        }
+    }
     Ok(mmap)
 }
 
-// On Windows there is no advice to give.
+// On Windows there is no advice to give, and mapping past the end of the file would grow it,
+// so map_size is ignored.
 #[cfg(windows)]
-fn mmap(file: &File, populate: bool) -> Result<Mmap> {
+fn mmap(file: &File, flags: &DBFlags) -> Result<Mmap> {
     let mmap = unsafe { Mmap::map(file)? };
     Ok(mmap)
-}
\ No newline at end of file
+}
+
+// Asks the OS to read in the pages every transaction starts from:
+// the meta pages, the freelist page and the root bucket's page.
+#[cfg(unix)]
+fn advise_hot_pages(mmap: &Mmap, file_size: u64, meta: &Meta, pagesize: u64) -> Result<()> {
+    use memmap2::Advice;
+
+    let len = file_size.min(mmap.len() as u64);
+    let ranges = [
+        (0, meta.num_meta_pages as u64),
+        (meta.freelist_page, 1),
+        (meta.root.root_page, 1),
+    ];
+    for (page_id, count) in ranges {
+        let range = page_id.checked_mul(pagesize).and_then(|start| {
+            let end = start.checked_add(count.checked_mul(pagesize)?)?;
+            Some((start, end))
+        });
+        // Advising outside the mapping is undefined behaviour, and the advice is only a hint,
+        // so ranges a corrupt meta page points past the end of the file are skipped.
+        if let Some((start, end)) = range.filter(|&(_, end)| end <= len) {
+            mmap.advise_range(Advice::WillNeed, start as usize, (end - start) as usize)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(windows)]
+fn advise_hot_pages(_mmap: &Mmap, _file_size: u64, _meta: &Meta, _pagesize: u64) -> Result<()> {
+    Ok(())
+}
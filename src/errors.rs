@@ -1,4 +1,12 @@
-use std::{error::Error as StdError, fmt, sync::PoisonError};
+use std::{
+    error::Error as StdError,
+    fmt,
+    path::PathBuf,
+    sync::{LockResult, PoisonError},
+};
+
+use crate::meta::USER_META_SIZE;
+use crate::page::{PageID, PageType};
 
 pub(crate) type Result<T> = std::result::Result<T, Error>;
 
@@ -21,9 +29,118 @@ pub enum Error {
     Sync(&'static str),
     /// Error returned when the DB is found to be in an invalid state
     InvalidDB(String),
+    /// Tried to open a database with a pagesize that is not a power of two from 1024 bytes to 1 MiB
+    InvalidPagesize(u64),
+    /// Tried to create a database with fewer pages than needed for its initial layout
+    InvalidNumPages { num_pages: usize, min: usize },
+    /// Tried to create a database whose initial size does not fit in a `u64`
+    FileTooLarge { pagesize: u64, num_pages: usize },
+    /// Tried to create a database with fewer than 2 or more than 64 meta pages
+    InvalidNumMetaPages(u32),
+    /// A meta page was written with a different pagesize than the one the database was opened with
+    PagesizeMismatch {
+        page_id: PageID,
+        expected: u64,
+        actual: u64,
+    },
+    /// A page was found to have a different type than the one expected at that location
+    InvalidPageType {
+        page_id: PageID,
+        expected: PageType,
+        actual: PageType,
+    },
+    /// A page id points past the end of the file, or a page's overflow runs past it
+    PageOutOfBounds { page_id: PageID, num_pages: u64 },
+    /// The id stored in a page's header does not match the location it was read from
+    PageIdMismatch { page_id: PageID, actual: PageID },
+    /// A page's header has a page type that does not exist
+    UnknownPageType {
+        page_id: PageID,
+        page_type: PageType,
+    },
+    /// A page's header claims more elements than fit in the page
+    PageCountOutOfRange {
+        page_id: PageID,
+        count: u64,
+        max: u64,
+    },
+    /// Neither of the meta pages passed its hash check
+    NoValidMeta,
+    /// Tried to decode bytes that are not a valid key encoding
+    InvalidKey(&'static str),
+    /// A bucket uses a comparator tag that was not registered when opening the database
+    UnknownComparator(u8),
+    /// Tried to register a custom comparator with a tag reserved for the built-in comparators
+    ReservedComparator(u8),
+    /// Tried to register two custom comparators with the same tag
+    DuplicateComparator(u8),
+    /// The database was written in a file format version this crate cannot read
+    UnsupportedVersion(u32),
+    /// A meta page passed its hash check but holds a value that is out of range
+    InvalidMeta {
+        page_id: PageID,
+        field: &'static str,
+        value: u64,
+    },
+    /// Tried to store more user metadata than fits in the meta page
+    UserMetaTooLarge(usize),
+    /// Growing the file would exceed the size set with [`OpenOptions::max_size`](crate::db::OpenOptions::max_size)
+    QuotaExceeded { max_size: u64, requested: u64 },
+    /// Tried to open a database that is already open in this process
+    AlreadyOpen(PathBuf),
+    /// Wraps an inconsistency found in a page with the transaction and bucket it was read for
+    Corrupted {
+        tx_id: u64,
+        /// Names of the buckets leading to the page, empty for pages outside of any bucket
+        bucket_path: Vec<Vec<u8>>,
+        source: Box<Error>,
+    },
+}
+
+impl Error {
+    /// Whether this error was caused by inconsistent data in the file,
+    /// rather than by invalid input or a failing operating system call.
+    ///
+    /// [`Error::PagesizeMismatch`] is not counted, it usually means the database was opened
+    /// with the wrong pagesize.
+    pub fn is_corruption(&self) -> bool {
+        matches!(
+            self,
+            Error::InvalidPageType { .. }
+                | Error::PageOutOfBounds { .. }
+                | Error::PageIdMismatch { .. }
+                | Error::UnknownPageType { .. }
+                | Error::PageCountOutOfRange { .. }
+                | Error::InvalidMeta { .. }
+                | Error::NoValidMeta
+                | Error::Corrupted { .. }
+        )
+    }
+
+    // Adds the transaction and bucket a page was read for to corruption errors,
+    // other errors are returned as they are.
+    pub(crate) fn corrupted(self, tx_id: u64, bucket_path: &[&[u8]]) -> Error {
+        match self {
+            Error::Corrupted { .. } => self,
+            e if e.is_corruption() => Error::Corrupted {
+                tx_id,
+                bucket_path: bucket_path.iter().map(|name| name.to_vec()).collect(),
+                source: Box::new(e),
+            },
+            e => e,
+        }
+    }
 }
 
-impl StdError for Error {}
+impl StdError for Error {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match self {
+            Error::Io(e) => Some(e),
+            Error::Corrupted { source, .. } => Some(source.as_ref()),
+            _ => None,
+        }
+    }
+}
 
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
@@ -36,6 +153,119 @@ impl fmt::Display for Error {
             Error::Io(e) => write!(f, "IO Error: {}", e),
             Error::Sync(s) => write!(f, "Sync Error: {}", s),
             Error::InvalidDB(s) => write!(f, "Invalid DB: {}", s),
+            Error::InvalidPagesize(pagesize) => {
+                write!(
+                    f,
+                    "Pagesize must be a power of two from 1024 to 1048576 bytes, got {}",
+                    pagesize
+                )
+            }
+            Error::InvalidNumPages { num_pages, min } => {
+                write!(f, "Must have a minimum of {} pages, got {}", min, num_pages)
+            }
+            Error::FileTooLarge {
+                pagesize,
+                num_pages,
+            } => write!(
+                f,
+                "{} pages of {} bytes do not fit in a file",
+                num_pages, pagesize
+            ),
+            Error::InvalidNumMetaPages(num_meta_pages) => write!(
+                f,
+                "Must have between 2 and 64 meta pages, got {}",
+                num_meta_pages
+            ),
+            Error::PagesizeMismatch {
+                page_id,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "Invalid pagesize from meta page {}: {}. Expected {}.",
+                page_id, actual, expected
+            ),
+            Error::InvalidPageType {
+                page_id,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "Invalid type for page {}: {}. Expected {}.",
+                page_id, actual, expected
+            ),
+            Error::PageOutOfBounds { page_id, num_pages } => write!(
+                f,
+                "Page {} is out of bounds, the file has {} pages",
+                page_id, num_pages
+            ),
+            Error::PageIdMismatch { page_id, actual } => {
+                write!(f, "Page {} has id {} in its header", page_id, actual)
+            }
+            Error::UnknownPageType { page_id, page_type } => {
+                write!(f, "Page {} has unknown type {}", page_id, page_type)
+            }
+            Error::PageCountOutOfRange {
+                page_id,
+                count,
+                max,
+            } => write!(
+                f,
+                "Page {} has {} elements, at most {} fit",
+                page_id, count, max
+            ),
+            Error::NoValidMeta => write!(f, "No valid meta pages"),
+            Error::InvalidKey(s) => write!(f, "Invalid key: {}", s),
+            Error::UnknownComparator(tag) => write!(f, "Unknown comparator tag {}", tag),
+            Error::ReservedComparator(tag) => {
+                write!(
+                    f,
+                    "Comparator tag {} is reserved for built-in comparators",
+                    tag
+                )
+            }
+            Error::DuplicateComparator(tag) => {
+                write!(f, "Comparator tag {} is registered more than once", tag)
+            }
+            Error::UnsupportedVersion(version) => {
+                write!(f, "Unsupported file format version {}", version)
+            }
+            Error::InvalidMeta {
+                page_id,
+                field,
+                value,
+            } => write!(f, "Invalid {} in meta page {}: {}", field, page_id, value),
+            Error::UserMetaTooLarge(len) => write!(
+                f,
+                "User metadata is {} bytes, at most {} fit in the meta page",
+                len, USER_META_SIZE
+            ),
+            Error::QuotaExceeded {
+                max_size,
+                requested,
+            } => write!(
+                f,
+                "Growing the database to {} bytes exceeds its quota of {} bytes",
+                requested, max_size
+            ),
+            Error::AlreadyOpen(path) => {
+                write!(f, "Database {} is already open", path.display())
+            }
+            Error::Corrupted {
+                tx_id,
+                bucket_path,
+                source,
+            } => {
+                write!(f, "Corruption in tx {}", tx_id)?;
+                if !bucket_path.is_empty() {
+                    let names: Vec<_> = bucket_path
+                        .iter()
+                        .map(|name| String::from_utf8_lossy(name))
+                        .collect();
+                    write!(f, " in bucket {}", names.join("/"))?;
+                }
+                write!(f, ": {}", source)
+            }
         }
     }
 }
@@ -46,9 +276,18 @@ impl From<std::io::Error> for Error {
     }
 }
 
-impl<T> From<PoisonError<T>> for Error {
-    fn from(_: PoisonError<T>) -> Error {
-        Error::Sync("lock poisoned")
+/// Recovers the guard from a poisoned lock.
+///
+/// None of the internal locks are held while user code runs, so a panic that
+/// poisons one of them never leaves the guarded state half-updated. Treating
+/// poisoning as fatal would instead make every later call on the DB fail.
+pub(crate) trait LockResultExt<G> {
+    fn recover(self) -> G;
+}
+
+impl<G> LockResultExt<G> for LockResult<G> {
+    fn recover(self) -> G {
+        self.unwrap_or_else(PoisonError::into_inner)
     }
 }
 
@@ -62,6 +301,136 @@ impl PartialEq for Error {
             (Error::ReadOnlyTx, Error::ReadOnlyTx) => true,
             (Error::Sync(s1), Error::Sync(s2)) => s1 == s2,
             (Error::InvalidDB(s1), Error::InvalidDB(s2)) => s1 == s2,
+            (Error::InvalidPagesize(p1), Error::InvalidPagesize(p2)) => p1 == p2,
+            (
+                Error::InvalidNumPages {
+                    num_pages: n1,
+                    min: m1,
+                },
+                Error::InvalidNumPages {
+                    num_pages: n2,
+                    min: m2,
+                },
+            ) => n1 == n2 && m1 == m2,
+            (Error::InvalidNumMetaPages(n1), Error::InvalidNumMetaPages(n2)) => n1 == n2,
+            (
+                Error::FileTooLarge {
+                    pagesize: p1,
+                    num_pages: n1,
+                },
+                Error::FileTooLarge {
+                    pagesize: p2,
+                    num_pages: n2,
+                },
+            ) => p1 == p2 && n1 == n2,
+            (
+                Error::PagesizeMismatch {
+                    page_id: id1,
+                    expected: e1,
+                    actual: a1,
+                },
+                Error::PagesizeMismatch {
+                    page_id: id2,
+                    expected: e2,
+                    actual: a2,
+                },
+            ) => id1 == id2 && e1 == e2 && a1 == a2,
+            (
+                Error::InvalidPageType {
+                    page_id: id1,
+                    expected: e1,
+                    actual: a1,
+                },
+                Error::InvalidPageType {
+                    page_id: id2,
+                    expected: e2,
+                    actual: a2,
+                },
+            ) => id1 == id2 && e1 == e2 && a1 == a2,
+            (
+                Error::PageOutOfBounds {
+                    page_id: id1,
+                    num_pages: n1,
+                },
+                Error::PageOutOfBounds {
+                    page_id: id2,
+                    num_pages: n2,
+                },
+            ) => id1 == id2 && n1 == n2,
+            (
+                Error::PageIdMismatch {
+                    page_id: id1,
+                    actual: a1,
+                },
+                Error::PageIdMismatch {
+                    page_id: id2,
+                    actual: a2,
+                },
+            ) => id1 == id2 && a1 == a2,
+            (
+                Error::UnknownPageType {
+                    page_id: id1,
+                    page_type: t1,
+                },
+                Error::UnknownPageType {
+                    page_id: id2,
+                    page_type: t2,
+                },
+            ) => id1 == id2 && t1 == t2,
+            (
+                Error::PageCountOutOfRange {
+                    page_id: id1,
+                    count: c1,
+                    max: m1,
+                },
+                Error::PageCountOutOfRange {
+                    page_id: id2,
+                    count: c2,
+                    max: m2,
+                },
+            ) => id1 == id2 && c1 == c2 && m1 == m2,
+            (Error::NoValidMeta, Error::NoValidMeta) => true,
+            (Error::InvalidKey(s1), Error::InvalidKey(s2)) => s1 == s2,
+            (Error::UnknownComparator(t1), Error::UnknownComparator(t2)) => t1 == t2,
+            (Error::ReservedComparator(t1), Error::ReservedComparator(t2)) => t1 == t2,
+            (Error::DuplicateComparator(t1), Error::DuplicateComparator(t2)) => t1 == t2,
+            (Error::UnsupportedVersion(v1), Error::UnsupportedVersion(v2)) => v1 == v2,
+            (
+                Error::InvalidMeta {
+                    page_id: id1,
+                    field: f1,
+                    value: v1,
+                },
+                Error::InvalidMeta {
+                    page_id: id2,
+                    field: f2,
+                    value: v2,
+                },
+            ) => id1 == id2 && f1 == f2 && v1 == v2,
+            (Error::UserMetaTooLarge(l1), Error::UserMetaTooLarge(l2)) => l1 == l2,
+            (
+                Error::QuotaExceeded {
+                    max_size: m1,
+                    requested: r1,
+                },
+                Error::QuotaExceeded {
+                    max_size: m2,
+                    requested: r2,
+                },
+            ) => m1 == m2 && r1 == r2,
+            (Error::AlreadyOpen(p1), Error::AlreadyOpen(p2)) => p1 == p2,
+            (
+                Error::Corrupted {
+                    tx_id: t1,
+                    bucket_path: b1,
+                    source: s1,
+                },
+                Error::Corrupted {
+                    tx_id: t2,
+                    bucket_path: b2,
+                    source: s2,
+                },
+            ) => t1 == t2 && b1 == b2 && s1 == s2,
             _ => false,
         }
     }
@@ -8,4 +8,67 @@ use crate::page::PageID;
 pub(crate) struct Freelist {
     free_pages: BTreeSet<PageID>,
     pending_pages: BTreeMap<u64, Vec<PageID>>,
-}
\ No newline at end of file
+}
+
+impl Freelist {
+    // Number of pages that are free to be reused, not counting pending ones
+    pub(crate) fn len(&self) -> usize {
+        self.free_pages.len()
+    }
+
+    // Moves the pages freed by transactions before `tx_id` from pending to free,
+    // returning how many pages were released
+    pub(crate) fn release(&mut self, tx_id: u64) -> usize {
+        let still_pending = self.pending_pages.split_off(&tx_id);
+        let released = std::mem::replace(&mut self.pending_pages, still_pending);
+        released
+            .into_values()
+            .flatten()
+            .map(|page_id| self.free_pages.insert(page_id))
+            .filter(|inserted| *inserted)
+            .count()
+    }
+
+    pub(crate) fn stats(&self) -> FreelistStats {
+        let mut largest_run = 0;
+        let mut run = 0;
+        let mut prev: Option<PageID> = None;
+        for &id in &self.free_pages {
+            run = match prev {
+                Some(prev) if prev + 1 == id => run + 1,
+                _ => 1,
+            };
+            largest_run = largest_run.max(run);
+            prev = Some(id);
+        }
+        let free_pages = self.free_pages.len() as u64;
+        let fragmentation = if free_pages == 0 {
+            0.0
+        } else {
+            1.0 - largest_run as f64 / free_pages as f64
+        };
+        FreelistStats {
+            free_pages,
+            pending_pages: self
+                .pending_pages
+                .iter()
+                .map(|(tx_id, pages)| (*tx_id, pages.len() as u64))
+                .collect(),
+            largest_run,
+            fragmentation,
+        }
+    }
+}
+
+/// Snapshot of the freelist's state, returned by [`DB::freelist_stats`](crate::db::DB::freelist_stats).
+#[derive(Debug, Clone, PartialEq)]
+pub struct FreelistStats {
+    /// Number of pages that can be reused by the next allocation
+    pub free_pages: u64,
+    /// Number of pages freed by each transaction that are still in use by open read transactions
+    pub pending_pages: BTreeMap<u64, u64>,
+    /// Length of the longest run of consecutive free pages
+    pub largest_run: u64,
+    /// Share of free pages outside of the longest run, from 0.0 (one contiguous run) to almost 1.0
+    pub fragmentation: f64,
+}
@@ -0,0 +1,56 @@
+//! Entry points for fuzzing the page parsers with arbitrary input.
+//!
+//! Only compiled with the `fuzzing` feature. Every function here takes untrusted bytes and
+//! must return an error for malformed input rather than panic or read out of bounds.
+use crate::db::check_pagesize;
+use crate::errors::{Error, Result};
+use crate::meta::Meta;
+use crate::page::Page;
+
+/// Parses the meta pages at the start of `data`, which holds a whole database file
+/// written with the given pagesize, running the same checks as opening a database.
+pub fn parse_meta_pages(data: &[u8], pagesize: u64) -> Result<()> {
+    let buf = aligned_pages(data, pagesize)?;
+    let buf = as_bytes(&buf);
+    let file_pages = buf.len() as u64 / pagesize;
+    let meta = Meta::newest(buf, pagesize, file_pages)?;
+    meta.user_meta()?;
+    Ok(())
+}
+
+/// Parses the header of page `page_id` in `data`, which holds a whole database file written
+/// with the given pagesize. Works for meta, freelist, leaf and branch pages alike: the id,
+/// type, overflow and element count are checked against the file before the page is used.
+pub fn parse_page(data: &[u8], page_id: u64, pagesize: u64) -> Result<()> {
+    let buf = aligned_pages(data, pagesize)?;
+    Page::from_buf_checked(as_bytes(&buf), page_id, pagesize)?;
+    Ok(())
+}
+
+// Copies the whole pages of `data` into an 8 byte aligned buffer,
+// since fuzzers hand out slices with arbitrary alignment.
+fn aligned_pages(data: &[u8], pagesize: u64) -> Result<Vec<u64>> {
+    check_pagesize(pagesize)?;
+    let pagesize = pagesize as usize;
+    let len = data.len() - data.len() % pagesize;
+    if len == 0 {
+        return Err(Error::InvalidDB(format!(
+            "file is truncated, got {} bytes, expected at least {}",
+            data.len(),
+            pagesize
+        )));
+    }
+    let mut buf = vec![0_u64; len / 8];
+    for (word, chunk) in buf.iter_mut().zip(data[..len].chunks(8)) {
+        let mut bytes = [0; 8];
+        bytes[..chunk.len()].copy_from_slice(chunk);
+        *word = u64::from_ne_bytes(bytes);
+    }
+    Ok(buf)
+}
+
+fn as_bytes(buf: &[u64]) -> &[u8] {
+    // SAFETY: u8 has no alignment requirement and no invalid bit patterns, and the slice
+    // covers exactly the buf.len() * 8 initialized bytes owned by `buf`, borrowed as long as it.
+    unsafe { std::slice::from_raw_parts(buf.as_ptr() as *const u8, buf.len() * 8) }
+}
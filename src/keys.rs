@@ -0,0 +1,211 @@
+//! Order-preserving key encodings.
+//!
+//! Keys are compared byte by byte, so integers, floats and composite keys have to be encoded
+//! such that the byte order matches the natural order of the values for range scans to work.
+//! Every type implementing [`KeyEncoding`] writes a self-delimiting encoding, which lets them
+//! be combined into tuples and wrapped in [`Reverse`] without breaking the ordering.
+//!
+//! ```ignore
+//! use bplustreedb::keys::{self, Reverse};
+//!
+//! let a = keys::encode(&("user".to_string(), Reverse(10u64)));
+//! let b = keys::encode(&("user".to_string(), Reverse(9u64)));
+//! assert!(a < b);
+//! assert_eq!(keys::decode::<(String, Reverse<u64>)>(&a)?, ("user".to_string(), Reverse(10)));
+//! ```
+use crate::errors::{Error, Result};
+
+/// A value that can be encoded into bytes which sort in the same order as the value.
+pub trait KeyEncoding: Sized {
+    /// Appends the encoded key to `buf`.
+    fn encode_key(&self, buf: &mut Vec<u8>);
+
+    /// Decodes a key from the front of `buf`, advancing it past the consumed bytes.
+    fn decode_key(buf: &mut &[u8]) -> Result<Self>;
+}
+
+/// Encodes a key into a new buffer.
+pub fn encode<K: KeyEncoding>(key: &K) -> Vec<u8> {
+    let mut buf = Vec::new();
+    key.encode_key(&mut buf);
+    buf
+}
+
+/// Decodes a key, failing if any bytes are left over.
+pub fn decode<K: KeyEncoding>(mut buf: &[u8]) -> Result<K> {
+    let key = K::decode_key(&mut buf)?;
+    if !buf.is_empty() {
+        return Err(Error::InvalidKey("trailing bytes after key"));
+    }
+    Ok(key)
+}
+
+/// Wrapper that sorts the inner key in descending order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct Reverse<T>(pub T);
+
+fn take<const N: usize>(buf: &mut &[u8]) -> Result<[u8; N]> {
+    if buf.len() < N {
+        return Err(Error::InvalidKey("key is too short"));
+    }
+    let mut bytes = [0; N];
+    bytes.copy_from_slice(&buf[..N]);
+    *buf = &buf[N..];
+    Ok(bytes)
+}
+
+macro_rules! unsigned_key {
+    ($($t:ty),*) => {$(
+        impl KeyEncoding for $t {
+            fn encode_key(&self, buf: &mut Vec<u8>) {
+                buf.extend_from_slice(&self.to_be_bytes());
+            }
+
+            fn decode_key(buf: &mut &[u8]) -> Result<Self> {
+                Ok(<$t>::from_be_bytes(take(buf)?))
+            }
+        }
+    )*};
+}
+
+unsigned_key!(u8, u16, u32, u64, u128);
+
+// Flipping the sign bit moves negative numbers below the positive ones
+// while keeping two's complement order within each half.
+macro_rules! signed_key {
+    ($($t:ty => $u:ty),*) => {$(
+        impl KeyEncoding for $t {
+            fn encode_key(&self, buf: &mut Vec<u8>) {
+                let flipped = (*self as $u) ^ (1 << (<$u>::BITS - 1));
+                buf.extend_from_slice(&flipped.to_be_bytes());
+            }
+
+            fn decode_key(buf: &mut &[u8]) -> Result<Self> {
+                let flipped = <$u>::from_be_bytes(take(buf)?);
+                Ok((flipped ^ (1 << (<$u>::BITS - 1))) as $t)
+            }
+        }
+    )*};
+}
+
+signed_key!(i8 => u8, i16 => u16, i32 => u32, i64 => u64, i128 => u128);
+
+// Floats are ordered the same way as `total_cmp`: negative values have all their
+// bits inverted, positive values only have their sign bit set.
+macro_rules! float_key {
+    ($($t:ty => $u:ty),*) => {$(
+        impl KeyEncoding for $t {
+            fn encode_key(&self, buf: &mut Vec<u8>) {
+                let bits = self.to_bits();
+                let sign = 1 << (<$u>::BITS - 1);
+                let ordered = if bits & sign != 0 { !bits } else { bits | sign };
+                buf.extend_from_slice(&ordered.to_be_bytes());
+            }
+
+            fn decode_key(buf: &mut &[u8]) -> Result<Self> {
+                let ordered = <$u>::from_be_bytes(take(buf)?);
+                let sign = 1 << (<$u>::BITS - 1);
+                let bits = if ordered & sign != 0 { ordered ^ sign } else { !ordered };
+                Ok(<$t>::from_bits(bits))
+            }
+        }
+    )*};
+}
+
+float_key!(f32 => u32, f64 => u64);
+
+// Variable length bytes escape every 0x00 as 0x00 0xFF and end with 0x00 0x00,
+// so a key is never a prefix of another one and tuples stay ordered field by field.
+impl KeyEncoding for Vec<u8> {
+    fn encode_key(&self, buf: &mut Vec<u8>) {
+        for &b in self {
+            buf.push(b);
+            if b == 0 {
+                buf.push(0xFF);
+            }
+        }
+        buf.extend_from_slice(&[0, 0]);
+    }
+
+    fn decode_key(buf: &mut &[u8]) -> Result<Self> {
+        let mut out = Vec::new();
+        let mut i = 0;
+        loop {
+            match (buf.get(i), buf.get(i + 1)) {
+                (Some(0), Some(0)) => break,
+                (Some(0), Some(0xFF)) => {
+                    out.push(0);
+                    i += 2;
+                }
+                (Some(0), _) | (None, _) => {
+                    return Err(Error::InvalidKey("unterminated byte string"))
+                }
+                (Some(&b), _) => {
+                    out.push(b);
+                    i += 1;
+                }
+            }
+        }
+        *buf = &buf[i + 2..];
+        Ok(out)
+    }
+}
+
+impl KeyEncoding for String {
+    fn encode_key(&self, buf: &mut Vec<u8>) {
+        // Same encoding as the bytes, without the copy.
+        for &b in self.as_bytes() {
+            buf.push(b);
+            if b == 0 {
+                buf.push(0xFF);
+            }
+        }
+        buf.extend_from_slice(&[0, 0]);
+    }
+
+    fn decode_key(buf: &mut &[u8]) -> Result<Self> {
+        String::from_utf8(Vec::<u8>::decode_key(buf)?)
+            .map_err(|_| Error::InvalidKey("string key is not valid UTF-8"))
+    }
+}
+
+// Inverting every byte of a self-delimiting encoding reverses its order.
+impl<T: KeyEncoding> KeyEncoding for Reverse<T> {
+    fn encode_key(&self, buf: &mut Vec<u8>) {
+        let start = buf.len();
+        self.0.encode_key(buf);
+        for b in &mut buf[start..] {
+            *b = !*b;
+        }
+    }
+
+    fn decode_key(buf: &mut &[u8]) -> Result<Self> {
+        let inverted: Vec<u8> = buf.iter().map(|b| !b).collect();
+        let mut rest = &inverted[..];
+        let key = T::decode_key(&mut rest)?;
+        *buf = &buf[inverted.len() - rest.len()..];
+        Ok(Reverse(key))
+    }
+}
+
+macro_rules! tuple_key {
+    ($($name:ident),+) => {
+        impl<$($name: KeyEncoding),+> KeyEncoding for ($($name,)+) {
+            #[allow(non_snake_case)]
+            fn encode_key(&self, buf: &mut Vec<u8>) {
+                let ($($name,)+) = self;
+                $($name.encode_key(buf);)+
+            }
+
+            fn decode_key(buf: &mut &[u8]) -> Result<Self> {
+                Ok(($($name::decode_key(buf)?,)+))
+            }
+        }
+    };
+}
+
+tuple_key!(A);
+tuple_key!(A, B);
+tuple_key!(A, B, C);
+tuple_key!(A, B, C, D);
+tuple_key!(A, B, C, D, E);
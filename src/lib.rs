@@ -1,11 +1,15 @@
 #[allow(clippy::mutable_key_type)]
 mod bucket;
 mod bytes;
+pub mod comparator;
 mod cursor;
 mod data;
 mod db;
 mod errors;
 mod freelist;
+#[cfg(feature = "fuzzing")]
+pub mod fuzzing;
+pub mod keys;
 mod lifetimes;
 mod meta;
 mod node;
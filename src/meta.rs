@@ -1,5 +1,10 @@
 use crate::bucket::BucketMeta;
-use crate::page::PageID;
+use crate::db::{MIN_META_PAGES, VERSION};
+use crate::errors::{Error, Result};
+use crate::page::{Page, PageID};
+
+/// Number of bytes in the meta page reserved for the application.
+pub const USER_META_SIZE: usize = 64;
 
 #[repr(C)]
 #[derive(Debug, Clone)]
@@ -7,15 +12,50 @@ pub(crate) struct Meta {
     pub(crate) meta_page: u32,
     pub(crate) magic: u32,
     pub(crate) version: u32,
+    // Number of meta pages at the start of the file, written round-robin on commit
+    pub(crate) num_meta_pages: u32,
     pub(crate) pagesize: u64,
     pub(crate) root: BucketMeta,
     pub(crate) num_pages: PageID,
     pub(crate) freelist_page: PageID,
     pub(crate) tx_id: u64,
+    // Application owned bytes, only the first user_meta_len are in use
+    pub(crate) user_meta_len: u8,
+    pub(crate) user_meta: [u8; USER_META_SIZE],
     pub(crate) hash: [u8; 32],
 }
 
 impl Meta {
+    // Finds the valid meta page with the highest tx id among the first pages of `buf`.
+    pub(crate) fn newest(buf: &[u8], pagesize: u64, file_pages: u64) -> Result<&Meta> {
+        let mut newest: Option<&Meta> = None;
+        // Every valid meta page knows how many meta pages there are. Only one of them
+        // is written per commit, so at least one of the first two is always valid.
+        let mut num_meta_pages = None;
+        let mut page_id = 0;
+        while page_id < num_meta_pages.unwrap_or(MIN_META_PAGES) as PageID {
+            let meta = Page::from_buf_checked(buf, page_id, pagesize).and_then(Page::meta);
+            if let Some(meta) = meta.ok().filter(|meta| meta.valid()) {
+                // Double check that we have the right pagesize before we read the next page.
+                // This also bounds num_meta_pages by the size of the file.
+                meta.check(page_id, pagesize, file_pages)?;
+                if *num_meta_pages.get_or_insert(meta.num_meta_pages) != meta.num_meta_pages {
+                    return Err(Error::InvalidMeta {
+                        page_id,
+                        field: "num_meta_pages",
+                        value: meta.num_meta_pages as u64,
+                    });
+                }
+                if newest.is_none_or(|newest| meta.tx_id > newest.tx_id) {
+                    newest = Some(meta);
+                }
+            }
+            page_id += 1;
+        }
+
+        newest.ok_or(Error::NoValidMeta)
+    }
+
     pub(crate) fn valid(&self) -> bool {
         self.hash == self.hash_self()
     }
@@ -30,5 +70,65 @@ impl Meta {
         hash_result
     }
 
+    // The hash only shows that a meta page is not torn, not that its fields are sane,
+    // so everything that is later used as an index or a bound is checked here as well.
+    pub(crate) fn check(&self, page_id: PageID, pagesize: u64, file_pages: u64) -> Result<()> {
+        if self.version != VERSION {
+            return Err(Error::UnsupportedVersion(self.version));
+        }
+        if self.pagesize != pagesize {
+            return Err(Error::PagesizeMismatch {
+                page_id,
+                expected: pagesize,
+                actual: self.pagesize,
+            });
+        }
+        let invalid = |field, value| Error::InvalidMeta {
+            page_id,
+            field,
+            value,
+        };
+        if self.num_pages > file_pages {
+            return Err(invalid("num_pages", self.num_pages));
+        }
+        // The meta pages are followed by at least the freelist page and the root bucket's page
+        let num_meta_pages = self.num_meta_pages as u64;
+        if num_meta_pages < MIN_META_PAGES as u64 || num_meta_pages + 2 > self.num_pages {
+            return Err(invalid("num_meta_pages", num_meta_pages));
+        }
+        for (field, page) in [
+            ("freelist_page", self.freelist_page),
+            ("root_page", self.root.root_page),
+        ] {
+            if page < num_meta_pages || page >= self.num_pages {
+                return Err(invalid(field, page));
+            }
+        }
+        if self.user_meta_len as usize > USER_META_SIZE {
+            return Err(invalid("user_meta_len", self.user_meta_len as u64));
+        }
+        Ok(())
+    }
+
+    pub(crate) fn user_meta(&self) -> Result<&[u8]> {
+        self.user_meta
+            .get(..self.user_meta_len as usize)
+            .ok_or(Error::InvalidMeta {
+                page_id: self.meta_page as PageID,
+                field: "user_meta_len",
+                value: self.user_meta_len as u64,
+            })
+    }
+
+    pub(crate) fn set_user_meta(&mut self, data: &[u8]) -> Result<()> {
+        if data.len() > USER_META_SIZE {
+            return Err(Error::UserMetaTooLarge(data.len()));
+        }
+        self.user_meta = [0; USER_META_SIZE];
+        self.user_meta[..data.len()].copy_from_slice(data);
+        self.user_meta_len = data.len() as u8;
+        Ok(())
+    }
+
 
 }
\ No newline at end of file
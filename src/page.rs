@@ -1,3 +1,6 @@
+use std::mem::{align_of, size_of};
+
+use crate::errors::{Error, Result};
 use crate::meta::Meta;
 
 pub(crate) type PageID = u64;
@@ -19,7 +22,14 @@ pub(crate) struct Page {
 }
 
 
+// Bytes before the page's data, which starts at the ptr field
+const PAGE_HEADER_SIZE: u64 = (size_of::<Page>() - size_of::<u64>()) as u64;
+
 impl Page {
+    pub(crate) const TYPE_BRANCH: PageType = 0x01;
+    pub(crate) const TYPE_LEAF: PageType = 0x02;
+    pub(crate) const TYPE_META: PageType = 0x03;
+    pub(crate) const TYPE_FREELIST: PageType = 0x04;
 
     #[inline]
     pub(crate) fn from_buf(buf: &[u8], id: PageID, pagesize: u64) -> &Page {
@@ -29,9 +39,71 @@ impl Page {
         }
     }
 
-    pub(crate) fn meta(&self) -> &Meta {
-        assert_eq!(self.page_type, Page::TYPE_META);
-        unsafe { &*(&self.ptr as *const u64 as *const Meta) }
+    /// Like [`Page::from_buf`], but validates the page before handing it out instead of
+    /// trusting the page id and the header read from the file.
+    pub(crate) fn from_buf_checked(buf: &[u8], id: PageID, pagesize: u64) -> Result<&Page> {
+        let num_pages = buf.len() as u64 / pagesize;
+        let out_of_bounds = Error::PageOutOfBounds {
+            page_id: id,
+            num_pages,
+        };
+        if id >= num_pages {
+            return Err(out_of_bounds);
+        }
+        let offset = (id * pagesize) as usize;
+        if !(buf[offset..].as_ptr() as usize).is_multiple_of(align_of::<Page>()) {
+            return Err(Error::InvalidDB(format!(
+                "page {} is not aligned, pagesize {} must be a multiple of {}",
+                id,
+                pagesize,
+                align_of::<Page>()
+            )));
+        }
+        let page = Page::from_buf(buf, id, pagesize);
+        if page.id != id {
+            return Err(Error::PageIdMismatch {
+                page_id: id,
+                actual: page.id,
+            });
+        }
+        let element_size = match page.page_type {
+            Page::TYPE_META => None,
+            Page::TYPE_FREELIST => Some(size_of::<PageID>() as u64),
+            // Branch and leaf elements take up at least one byte each
+            Page::TYPE_BRANCH | Page::TYPE_LEAF => Some(1),
+            page_type => {
+                return Err(Error::UnknownPageType {
+                    page_id: id,
+                    page_type,
+                })
+            }
+        };
+        match page.overflow.checked_add(id + 1) {
+            Some(end) if end <= num_pages => (),
+            _ => return Err(out_of_bounds),
+        }
+        if let Some(element_size) = element_size {
+            let max = ((page.overflow + 1) * pagesize - PAGE_HEADER_SIZE) / element_size;
+            if page.count > max {
+                return Err(Error::PageCountOutOfRange {
+                    page_id: id,
+                    count: page.count,
+                    max,
+                });
+            }
+        }
+        Ok(page)
+    }
+
+    pub(crate) fn meta(&self) -> Result<&Meta> {
+        if self.page_type != Page::TYPE_META {
+            return Err(Error::InvalidPageType {
+                page_id: self.id,
+                expected: Page::TYPE_META,
+                actual: self.page_type,
+            });
+        }
+        Ok(unsafe { &*(&self.ptr as *const u64 as *const Meta) })
     }
 }
 